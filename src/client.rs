@@ -0,0 +1,306 @@
+// HTTP client used to talk to Reddit: request signing, OAuth token pool, and media proxying.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime};
+
+use arc_swap::ArcSwap;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Request, Response};
+use hyper_rustls::HttpsConnector;
+use log::{error, info, warn};
+
+/// Reddit's public Android OAuth client id, used for the installed-client grant (no client secret required).
+const OAUTH_CLIENT_ID: &str = "ohXpoqrZYub1Kg";
+/// User-Agent sent on the installed-client auth handshake, matching the spoofed Android client.
+const OAUTH_USER_AGENT: &str = "android:com.reddit.frontpage:v-redlib (by /u/redlib)";
+
+/// Plain HTTPS client used for requests that don't need Reddit's OAuth API (GitHub, static JSON, etc).
+pub static CLIENT: LazyLock<Client<HttpsConnector<HttpConnector>>> = LazyLock::new(|| {
+	let connector = hyper_rustls::HttpsConnectorBuilder::new().with_native_roots().https_or_http().enable_http1().build();
+	Client::builder().build(connector)
+});
+
+/// How many tokens we keep warm in the pool at once. Spreads rate limits across several spoofed sessions.
+const OAUTH_POOL_SIZE: usize = 4;
+/// Re-authenticate a token this long before Reddit's own expiry, so it never goes stale mid-request.
+const OAUTH_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+/// How often the background task wakes up to check for tokens that need refreshing.
+const OAUTH_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+/// How often the health probe re-checks Reddit's rate limit for `/readyz`.
+const HEALTH_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Live readiness state, refreshed by `spawn_health_probe` and read by the `/readyz` handler. Published the same lock-free way as the token pool so the probe never blocks a request.
+#[derive(Clone)]
+pub struct HealthState {
+	pub oauth_ready: bool,
+	pub rate_limit_ok: bool,
+	pub checked_at: SystemTime,
+}
+
+impl Default for HealthState {
+	fn default() -> Self {
+		Self {
+			oauth_ready: false,
+			rate_limit_ok: false,
+			checked_at: SystemTime::now(),
+		}
+	}
+}
+
+pub static HEALTH: LazyLock<ArcSwap<HealthState>> = LazyLock::new(|| ArcSwap::from_pointee(HealthState::default()));
+
+/// Spawn the background task that keeps `HEALTH` current: re-runs `rate_limit_check` on an interval
+/// and records whether the OAuth pool currently holds an unexpired token, so `/readyz` reflects the
+/// live ability to serve Reddit content rather than a one-time startup check.
+pub fn spawn_health_probe() {
+	tokio::spawn(async move {
+		loop {
+			let rate_limit_ok = rate_limit_check().await.is_ok();
+			// Checking pool occupancy directly (rather than via `next_oauth_token`) so a health probe
+			// never consumes a round-robin slot meant for real proxy traffic. Also requires at least
+			// one token to be genuinely unexpired, not merely present: a pool whose refresh keeps
+			// failing past real expiry should report not-ready rather than a stale false positive.
+			let oauth_ready = OAUTH_CLIENT.load().iter().any(|t| !t.is_expired());
+
+			HEALTH.store(std::sync::Arc::new(HealthState {
+				oauth_ready,
+				rate_limit_ok,
+				checked_at: SystemTime::now(),
+			}));
+
+			tokio::time::sleep(HEALTH_PROBE_INTERVAL).await;
+		}
+	});
+}
+
+/// A single spoofed OAuth session against Reddit's installed-client grant.
+#[derive(Clone)]
+pub struct OauthToken {
+	pub access_token: String,
+	pub device_id: String,
+	pub expires_at: SystemTime,
+}
+
+impl OauthToken {
+	fn needs_refresh(&self) -> bool {
+		SystemTime::now() + OAUTH_REFRESH_MARGIN >= self.expires_at
+	}
+
+	/// True once Reddit's own expiry has actually passed, as opposed to `needs_refresh`'s earlier
+	/// margin. A token can sit past `needs_refresh` but still be live if repeated refresh attempts
+	/// are failing (e.g. Reddit rejecting the client id) rather than simply pending rotation.
+	fn is_expired(&self) -> bool {
+		SystemTime::now() >= self.expires_at
+	}
+}
+
+/// Current pool of live tokens. Readers take a lock-free snapshot via `load()`; the rotation task
+/// and failure handler publish new pools via `store()` without ever blocking an in-flight request.
+pub static OAUTH_CLIENT: LazyLock<ArcSwap<Vec<OauthToken>>> = LazyLock::new(|| ArcSwap::from_pointee(Vec::new()));
+
+static NEXT_TOKEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Mint a single fresh token by authenticating against Reddit's OAuth endpoint as a spoofed Android client.
+async fn mint_token() -> Result<OauthToken, String> {
+	let device_id = uuid::Uuid::new_v4().to_string();
+	let body = format!("grant_type=https://oauth.reddit.com/grants/installed_client&device_id={device_id}");
+
+	let request = Request::post("https://www.reddit.com/auth/token")
+		.header("Authorization", format!("Basic {}", BASE64.encode(format!("{OAUTH_CLIENT_ID}:"))))
+		.header("Content-Type", "application/x-www-form-urlencoded")
+		.header("User-Agent", OAUTH_USER_AGENT)
+		.body(Body::from(body))
+		.map_err(|e| e.to_string())?;
+
+	let response = CLIENT.request(request).await.map_err(|e| e.to_string())?;
+	if !response.status().is_success() {
+		return Err(format!("Reddit OAuth endpoint returned {}", response.status()));
+	}
+
+	let body = hyper::body::to_bytes(response.into_body()).await.map_err(|e| e.to_string())?;
+	let json: serde_json::Value = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+
+	let access_token = json["access_token"].as_str().ok_or("OAuth response missing access_token")?.to_string();
+	let expires_in = json["expires_in"].as_u64().unwrap_or(3600);
+
+	Ok(OauthToken {
+		access_token,
+		device_id,
+		expires_at: SystemTime::now() + Duration::from_secs(expires_in),
+	})
+}
+
+/// Pick the next token round-robin from the current pool snapshot. Returns `None` if the pool hasn't
+/// been populated yet (e.g. during the very first startup race).
+pub fn next_oauth_token() -> Option<OauthToken> {
+	let pool = OAUTH_CLIENT.load();
+	if pool.is_empty() {
+		return None;
+	}
+	let idx = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed) % pool.len();
+	Some(pool[idx].clone())
+}
+
+/// Fill the pool up to `OAUTH_POOL_SIZE`, minting whatever tokens are missing. Called at startup and
+/// again by the rotation task on every tick, so a pool left short by a failed mint is topped up.
+pub async fn populate_oauth_pool() {
+	let missing = OAUTH_POOL_SIZE.saturating_sub(OAUTH_CLIENT.load().len());
+	if missing == 0 {
+		return;
+	}
+
+	let mut minted = Vec::with_capacity(missing);
+	for _ in 0..missing {
+		match mint_token().await {
+			Ok(token) => minted.push(token),
+			Err(e) => {
+				error!("Failed to mint OAuth token: {e}");
+				break;
+			}
+		}
+	}
+	if minted.is_empty() {
+		return;
+	}
+
+	// `rcu` re-applies the closure against the latest snapshot if another writer (the rotation task,
+	// a concurrent `replace_token`) stores in between, so the merge can never silently lose an update.
+	OAUTH_CLIENT.rcu(|pool| {
+		let mut pool = (**pool).clone();
+		pool.extend(minted.iter().cloned());
+		pool
+	});
+}
+
+/// Swap out a single failing token (401/403/429 from upstream) for a freshly minted one, leaving the
+/// rest of the pool untouched. The swap is lock-free, so requests already in flight against other
+/// tokens in the pool are unaffected.
+///
+/// No-ops if `failing_access_token` is no longer present in the pool: that means a concurrent
+/// caller (another request that hit the same failing token, or the expiry sweep in
+/// `spawn_oauth_rotation`) already replaced it, so pushing here would leave two live tokens behind
+/// and grow the pool past `OAUTH_POOL_SIZE` on every such race.
+pub async fn replace_token(failing_access_token: &str) {
+	let token = match mint_token().await {
+		Ok(token) => token,
+		Err(e) => {
+			error!("Failed to replace rate-limited OAuth token: {e}");
+			return;
+		}
+	};
+
+	OAUTH_CLIENT.rcu(|pool| {
+		let mut pool = (**pool).clone();
+		if let Some(slot) = pool.iter_mut().find(|t| t.access_token == failing_access_token) {
+			*slot = token.clone();
+		}
+		pool
+	});
+}
+
+/// Spawn the background task that keeps the token pool warm: fills it at startup and re-authenticates
+/// any token within `OAUTH_REFRESH_MARGIN` of expiry, forever, without ever blocking request handling.
+pub fn spawn_oauth_rotation() {
+	tokio::spawn(async move {
+		populate_oauth_pool().await;
+		info!("OAuth token pool populated with {} tokens", OAUTH_CLIENT.load().len());
+
+		loop {
+			tokio::time::sleep(OAUTH_REFRESH_INTERVAL).await;
+
+			let expiring: Vec<String> = OAUTH_CLIENT.load().iter().filter(|t| t.needs_refresh()).map(|t| t.access_token.clone()).collect();
+
+			for access_token in expiring {
+				replace_token(&access_token).await;
+			}
+
+			populate_oauth_pool().await;
+		}
+	});
+}
+
+/// Probe Reddit with a single listing request. Returns `Ok` once any response arrives; neither the
+/// status code nor the rate-limit headers are inspected, so only a failure to reach Reddit at all is
+/// reported as an error. Run at startup by `main` and on every interval by `spawn_health_probe`.
+pub async fn rate_limit_check() -> Result<(), String> {
+	let uri = "https://www.reddit.com/r/popular/hot.json?limit=1&raw_json=1".parse().map_err(|e| format!("{e}"))?;
+	CLIENT.get(uri).await.map_err(|e| e.to_string())?;
+	Ok(())
+}
+
+pub async fn canonical_path(_path: &str) -> Result<Option<String>, String> {
+	Ok(None)
+}
+
+/// Stream a response from an upstream CDN (`format`, with `{name}` placeholders filled in from the
+/// route's params) straight through to the client, authenticating with a token picked round-robin
+/// from the pool and retiring that same token if Reddit rejects it.
+pub async fn proxy(req: Request<Body>, format: &str) -> Result<Response<Body>, String> {
+	let mut url = format.to_string();
+	for (name, value) in req.params() {
+		url = url.replace(&format!("{{{name}}}"), &value);
+	}
+
+	let uri = url.parse::<hyper::Uri>().map_err(|e| format!("Invalid proxy target {url}: {e}"))?;
+
+	let token = next_oauth_token();
+	let mut builder = Request::get(uri);
+	if let Some(token) = &token {
+		builder = builder.header("Authorization", format!("Bearer {}", token.access_token));
+	}
+	let request = builder.body(Body::empty()).map_err(|e| e.to_string())?;
+
+	let response = CLIENT.request(request).await.map_err(|e| e.to_string())?;
+
+	if matches!(response.status().as_u16(), 401 | 403 | 429) {
+		if let Some(token) = token {
+			replace_token(&token.access_token).await;
+			warn!("Retired rate-limited/unauthorized OAuth token while proxying {url}");
+		}
+	}
+
+	Ok(strip_upstream_headers(response, &crate::config::CONFIG.strip_headers))
+}
+
+/// Remove headers the operator doesn't want leaking from the upstream CDN (via
+/// `REDLIB_STRIP_HEADERS`, defaulting to network-error-logging/reporting headers).
+fn strip_upstream_headers(mut response: Response<Body>, strip: &std::collections::HashSet<String>) -> Response<Body> {
+	response.headers_mut().retain(|name, _| !strip.contains(name.as_str()));
+	response
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::HashSet;
+
+	fn response_with_headers(headers: &[(&str, &str)]) -> Response<Body> {
+		let mut builder = Response::builder();
+		for (name, value) in headers {
+			builder = builder.header(*name, *value);
+		}
+		builder.body(Body::empty()).unwrap()
+	}
+
+	#[test]
+	fn strip_upstream_headers_removes_only_denylisted_headers() {
+		let response = response_with_headers(&[("Nel", "..."), ("Report-To", "..."), ("Content-Type", "image/png")]);
+		let strip: HashSet<String> = ["nel", "report-to"].into_iter().map(String::from).collect();
+
+		let response = strip_upstream_headers(response, &strip);
+
+		assert!(response.headers().get("Nel").is_none());
+		assert!(response.headers().get("Report-To").is_none());
+		assert_eq!(response.headers().get("Content-Type").unwrap(), "image/png");
+	}
+
+	#[test]
+	fn strip_upstream_headers_is_noop_for_an_empty_denylist() {
+		let response = response_with_headers(&[("Nel", "...")]);
+		let response = strip_upstream_headers(response, &HashSet::new());
+		assert!(response.headers().get("Nel").is_some());
+	}
+}
@@ -0,0 +1,41 @@
+// Instance-wide configuration, loaded once from environment variables at startup.
+
+use std::collections::HashSet;
+use std::env;
+use std::sync::LazyLock;
+
+pub struct Config {
+	/// Upstream response headers stripped from every proxied CDN response before it reaches the
+	/// browser. Lower-cased for case-insensitive comparison against `HeaderName`.
+	pub strip_headers: HashSet<String>,
+}
+
+pub static CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+	strip_headers: match get_setting("REDLIB_STRIP_HEADERS") {
+		Some(value) => value.split(',').map(|h| h.trim().to_lowercase()).filter(|h| !h.is_empty()).collect(),
+		None => default_strip_headers(),
+	},
+});
+
+fn default_strip_headers() -> HashSet<String> {
+	["nel", "report-to"].into_iter().map(String::from).collect()
+}
+
+/// Look up a setting by environment variable name. This is the generic escape hatch for options
+/// that don't warrant a dedicated `Config` field.
+pub fn get_setting(name: &str) -> Option<String> {
+	env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn default_strip_headers_covers_network_error_logging_and_reporting() {
+		let headers = default_strip_headers();
+		assert!(headers.contains("nel"));
+		assert!(headers.contains("report-to"));
+		assert_eq!(headers.len(), 2);
+	}
+}
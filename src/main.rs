@@ -11,13 +11,11 @@ use futures_lite::FutureExt;
 use hyper::Uri;
 use hyper::{header::HeaderValue, Body, Request, Response};
 use log::{info, warn};
-use redlib::client::{canonical_path, proxy, rate_limit_check, CLIENT};
+use redlib::client::{canonical_path, populate_oauth_pool, proxy, rate_limit_check, spawn_health_probe, spawn_oauth_rotation, CLIENT, HEALTH};
 use redlib::server::{self, RequestExt};
 use redlib::utils::{error, redirect, ThemeAssets};
 use redlib::{config, duplicates, headers, instance_info, post, search, settings, subreddit, user};
 
-use redlib::client::OAUTH_CLIENT;
-
 // Create Services
 
 // Required for the manifest to be valid
@@ -108,6 +106,40 @@ async fn style() -> Result<Response<Body>, String> {
 	)
 }
 
+// Always 200 once the listener is up; orchestrators use this to confirm the process is alive.
+async fn healthz() -> Result<Response<Body>, String> {
+	Ok(
+		Response::builder()
+			.status(200)
+			.header("content-type", "application/json")
+			.body(r#"{"status":"ok"}"#.into())
+			.unwrap_or_default(),
+	)
+}
+
+// Reports whether this instance can currently serve Reddit content, so a degraded instance can be
+// pulled out of rotation instead of receiving traffic it can't answer.
+async fn readyz() -> Result<Response<Body>, String> {
+	let health = HEALTH.load();
+	let ready = health.oauth_ready && health.rate_limit_ok;
+
+	let body = format!(
+		r#"{{"ready":{},"oauth_ready":{},"rate_limit_ok":{},"checked_at":{}}}"#,
+		ready,
+		health.oauth_ready,
+		health.rate_limit_ok,
+		health.checked_at.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs(),
+	);
+
+	Ok(
+		Response::builder()
+			.status(if ready { 200 } else { 503 })
+			.header("content-type", "application/json")
+			.body(body.into())
+			.unwrap_or_default(),
+	)
+}
+
 #[tokio::main]
 async fn main() {
 	// Load environment variables
@@ -125,7 +157,7 @@ async fn main() {
 			Arg::new("redirect-https")
 				.short('r')
 				.long("redirect-https")
-				.help("Redirect all HTTP requests to HTTPS (no longer functional)")
+				.help("Redirect all HTTP requests to HTTPS")
 				.num_args(0),
 		)
 		.arg(
@@ -192,6 +224,7 @@ async fn main() {
 
 	// Begin constructing a server
 	let mut app = server::Server::new();
+	app.redirect_https = matches.get_flag("redirect-https");
 
 	// Force evaluation of statics. In instance_info case, we need to evaluate
 	// the timestamp so deploy date is accurate - in config case, we need to
@@ -203,8 +236,10 @@ async fn main() {
 	LazyLock::force(&config::CONFIG);
 	info!("Evaluating instance info.");
 	LazyLock::force(&instance_info::INSTANCE_INFO);
-	info!("Creating OAUTH client.");
-	LazyLock::force(&OAUTH_CLIENT);
+	info!("Populating OAuth token pool.");
+	populate_oauth_pool().await;
+	spawn_oauth_rotation();
+	spawn_health_probe();
 
 	// Define default headers (added to all responses)
 	app.default_headers = headers! {
@@ -297,6 +332,10 @@ async fn main() {
 	app.at("/info").get(|r| instance_info::instance_info(r).boxed());
 	app.at("/info.:extension").get(|r| instance_info::instance_info(r).boxed());
 
+	// Container/orchestrator health checks
+	app.at("/healthz").get(|_| healthz().boxed());
+	app.at("/readyz").get(|_| readyz().boxed());
+
 	// Default service in case no routes match
 	app.at("/*").get(|req| error(req, "Nothing here").boxed());
 
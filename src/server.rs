@@ -0,0 +1,349 @@
+// Minimal routing layer on top of hyper: path matching, request extensions, and the middleware
+// hooks that run before a route is dispatched.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use hyper::header::HeaderValue;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, HeaderMap, Method, Request, Response, Server as HyperServer};
+
+use crate::utils::redirect;
+
+pub type Params = HashMap<String, String>;
+type AsyncHandler = Arc<dyn Fn(Request<Body>) -> Pin<Box<dyn Future<Output = Result<Response<Body>, String>> + Send>> + Send + Sync>;
+
+#[derive(Default, Clone)]
+struct Route {
+	get: Option<AsyncHandler>,
+	post: Option<AsyncHandler>,
+}
+
+struct Matched<'a> {
+	route: &'a Route,
+	params: Params,
+}
+
+pub struct Server {
+	routes: Vec<(Vec<String>, Route)>,
+	pub default_headers: HeaderMap<HeaderValue>,
+	/// Honor `X-Forwarded-Proto`/the request scheme and 301 plaintext requests to HTTPS. Set from
+	/// the `--redirect-https`/`-r` CLI flag.
+	pub redirect_https: bool,
+}
+
+pub struct RouteBuilder<'a> {
+	server: &'a mut Server,
+	segments: Vec<String>,
+}
+
+impl Server {
+	pub fn new() -> Self {
+		Self {
+			routes: Vec::new(),
+			default_headers: HeaderMap::new(),
+			redirect_https: false,
+		}
+	}
+
+	pub fn at(&mut self, path: &str) -> RouteBuilder<'_> {
+		RouteBuilder {
+			server: self,
+			segments: path.split('/').filter(|s| !s.is_empty()).map(str::to_owned).collect(),
+		}
+	}
+
+	fn recognize(&self, path: &str) -> Option<Matched<'_>> {
+		let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+		'routes: for (pattern, route) in &self.routes {
+			let mut params = Params::new();
+
+			for (i, part) in pattern.iter().enumerate() {
+				if let Some(name) = part.strip_prefix('*') {
+					params.insert(name.to_string(), segments[i..].join("/"));
+					return Some(Matched { route, params });
+				}
+				let Some(segment) = segments.get(i) else { continue 'routes };
+				if let Some(name) = part.strip_prefix(':') {
+					params.insert(name.to_string(), (*segment).to_string());
+				} else if part != segment {
+					continue 'routes;
+				}
+			}
+
+			if segments.len() == pattern.len() {
+				return Some(Matched { route, params });
+			}
+		}
+
+		None
+	}
+
+	pub async fn listen(self, addr: &str) -> Result<(), hyper::Error> {
+		let addr: SocketAddr = addr.parse().expect("Invalid listening address");
+		let this = Arc::new(self);
+
+		let make_svc = make_service_fn(move |_conn| {
+			let this = this.clone();
+			async move {
+				Ok::<_, Infallible>(service_fn(move |req| {
+					let this = this.clone();
+					async move { Ok::<_, Infallible>(this.handle(req).await) }
+				}))
+			}
+		});
+
+		HyperServer::bind(&addr).serve(make_svc).await
+	}
+
+	async fn handle(&self, req: Request<Body>) -> Response<Body> {
+		if let Some(resp) = redirect_https_middleware(&req, self.redirect_https) {
+			return self.with_default_headers(resp);
+		}
+		if let Some(resp) = normalize_path_middleware(&req) {
+			return self.with_default_headers(resp);
+		}
+
+		let path = req.uri().path().to_owned();
+
+		let Some(matched) = self.recognize(&path) else {
+			return self.with_default_headers(error_response());
+		};
+
+		let handler = match *req.method() {
+			Method::POST => matched.route.post.clone(),
+			_ => matched.route.get.clone(),
+		};
+
+		let response = match handler {
+			Some(handler) => {
+				let mut req = req;
+				req.extensions_mut().insert(matched.params);
+				match handler(req).await {
+					Ok(resp) => resp,
+					Err(e) => {
+						log::error!("Handler error: {e}");
+						error_response()
+					}
+				}
+			}
+			None => error_response(),
+		};
+
+		self.with_default_headers(response)
+	}
+
+	fn with_default_headers(&self, mut response: Response<Body>) -> Response<Body> {
+		for (name, value) in &self.default_headers {
+			response.headers_mut().entry(name).or_insert_with(|| value.clone());
+		}
+		response
+	}
+}
+
+impl<'a> RouteBuilder<'a> {
+	pub fn get<F>(self, handler: impl Fn(Request<Body>) -> F + Send + Sync + 'static) -> Self
+	where
+		F: Future<Output = Result<Response<Body>, String>> + Send + 'static,
+	{
+		self.insert(true, handler)
+	}
+
+	pub fn post<F>(self, handler: impl Fn(Request<Body>) -> F + Send + Sync + 'static) -> Self
+	where
+		F: Future<Output = Result<Response<Body>, String>> + Send + 'static,
+	{
+		self.insert(false, handler)
+	}
+
+	fn insert<F>(self, is_get: bool, handler: impl Fn(Request<Body>) -> F + Send + Sync + 'static) -> Self
+	where
+		F: Future<Output = Result<Response<Body>, String>> + Send + 'static,
+	{
+		let handler: AsyncHandler = Arc::new(move |req| Box::pin(handler(req)));
+
+		if let Some((_, route)) = self.server.routes.iter_mut().find(|(segments, _)| *segments == self.segments) {
+			if is_get {
+				route.get = Some(handler);
+			} else {
+				route.post = Some(handler);
+			}
+		} else {
+			let mut route = Route::default();
+			if is_get {
+				route.get = Some(handler);
+			} else {
+				route.post = Some(handler);
+			}
+			self.server.routes.push((self.segments.clone(), route));
+		}
+
+		self
+	}
+}
+
+fn error_response() -> Response<Body> {
+	Response::builder().status(404).body(Body::from("Nothing here")).unwrap_or_default()
+}
+
+/// Determine whether the incoming request arrived over plaintext HTTP, preferring the
+/// `X-Forwarded-Proto` header set by a TLS-terminating reverse proxy and falling back to the
+/// request URI's own scheme for direct connections.
+fn is_plaintext(req: &Request<Body>) -> bool {
+	if let Some(proto) = req.headers().get("X-Forwarded-Proto").and_then(|v| v.to_str().ok()) {
+		return proto.eq_ignore_ascii_case("http");
+	}
+	req.uri().scheme_str().is_none_or(|scheme| scheme.eq_ignore_ascii_case("http"))
+}
+
+/// When `--redirect-https` is set, force every plaintext request to the `https://` form of the
+/// same URL before it reaches routing, so the flag is honored for every route including the
+/// static-asset and media-proxy handlers registered in `main`. A request without a `Host` header
+/// has no target to redirect to and is passed through unchanged.
+fn redirect_https_middleware(req: &Request<Body>, enabled: bool) -> Option<Response<Body>> {
+	if !enabled || !is_plaintext(req) {
+		return None;
+	}
+
+	let host = req.headers().get("Host").and_then(|v| v.to_str().ok())?;
+	let path_and_query = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+	Some(redirect(&format!("https://{host}{path_and_query}")))
+}
+
+/// Collapse duplicated slashes and strip a trailing slash, redirecting to the canonical path
+/// (preserving the query string) so shared/bookmarked URLs with a stray `/` don't fall through to
+/// the `/*` "Nothing here" handler registered in `main`.
+fn normalize_path_middleware(req: &Request<Body>) -> Option<Response<Body>> {
+	let path = req.uri().path();
+
+	let mut normalized = String::with_capacity(path.len());
+	let mut last_was_slash = false;
+	for c in path.chars() {
+		if c == '/' {
+			if last_was_slash {
+				continue;
+			}
+			last_was_slash = true;
+		} else {
+			last_was_slash = false;
+		}
+		normalized.push(c);
+	}
+	if normalized.len() > 1 && normalized.ends_with('/') {
+		normalized.pop();
+	}
+
+	if normalized == path {
+		return None;
+	}
+
+	if let Some(query) = req.uri().query() {
+		normalized.push('?');
+		normalized.push_str(query);
+	}
+
+	Some(redirect(&normalized))
+}
+
+pub trait RequestExt {
+	fn params(&self) -> Params;
+}
+
+impl RequestExt for Request<Body> {
+	fn params(&self) -> Params {
+		self.extensions().get::<Params>().cloned().unwrap_or_default()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn get(uri: &str) -> Request<Body> {
+		Request::builder().uri(uri).body(Body::empty()).unwrap()
+	}
+
+	#[test]
+	fn is_plaintext_trusts_forwarded_proto_over_the_request_scheme() {
+		let req = Request::builder()
+			.uri("https://example.com/")
+			.header("X-Forwarded-Proto", "http")
+			.body(Body::empty())
+			.unwrap();
+		assert!(is_plaintext(&req));
+
+		let req = Request::builder()
+			.uri("http://example.com/")
+			.header("X-Forwarded-Proto", "https")
+			.body(Body::empty())
+			.unwrap();
+		assert!(!is_plaintext(&req));
+	}
+
+	#[test]
+	fn is_plaintext_falls_back_to_the_request_scheme() {
+		assert!(is_plaintext(&get("http://example.com/")));
+		assert!(!is_plaintext(&get("https://example.com/")));
+	}
+
+	#[test]
+	fn is_plaintext_treats_a_schemeless_uri_as_plaintext() {
+		assert!(is_plaintext(&get("/r/popular")));
+	}
+
+	#[test]
+	fn redirect_https_middleware_is_noop_when_disabled() {
+		let req = Request::builder().uri("/r/popular").header("Host", "example.com").body(Body::empty()).unwrap();
+		assert!(redirect_https_middleware(&req, false).is_none());
+	}
+
+	#[test]
+	fn redirect_https_middleware_is_noop_for_already_encrypted_requests() {
+		let req = Request::builder()
+			.uri("/r/popular")
+			.header("Host", "example.com")
+			.header("X-Forwarded-Proto", "https")
+			.body(Body::empty())
+			.unwrap();
+		assert!(redirect_https_middleware(&req, true).is_none());
+	}
+
+	#[test]
+	fn redirect_https_middleware_redirects_plaintext_requests_to_https() {
+		let req = Request::builder().uri("/r/popular?sort=hot").header("Host", "example.com").body(Body::empty()).unwrap();
+		let resp = redirect_https_middleware(&req, true).expect("plaintext request should redirect");
+
+		assert_eq!(resp.status(), 301);
+		assert_eq!(resp.headers().get("Location").unwrap(), "https://example.com/r/popular?sort=hot");
+	}
+
+	#[test]
+	fn normalize_path_middleware_is_noop_for_already_canonical_paths() {
+		assert!(normalize_path_middleware(&get("/r/popular")).is_none());
+		assert!(normalize_path_middleware(&get("/")).is_none());
+	}
+
+	#[test]
+	fn normalize_path_middleware_strips_a_trailing_slash() {
+		let resp = normalize_path_middleware(&get("/settings/")).expect("trailing slash should redirect");
+		assert_eq!(resp.status(), 301);
+		assert_eq!(resp.headers().get("Location").unwrap(), "/settings");
+	}
+
+	#[test]
+	fn normalize_path_middleware_collapses_duplicate_slashes() {
+		let resp = normalize_path_middleware(&get("/r//popular//hot")).expect("duplicate slashes should redirect");
+		assert_eq!(resp.headers().get("Location").unwrap(), "/r/popular/hot");
+	}
+
+	#[test]
+	fn normalize_path_middleware_preserves_the_query_string() {
+		let resp = normalize_path_middleware(&get("/r/popular/?sort=hot")).expect("trailing slash should redirect");
+		assert_eq!(resp.headers().get("Location").unwrap(), "/r/popular?sort=hot");
+	}
+}